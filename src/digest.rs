@@ -0,0 +1,53 @@
+use clap::ValueEnum;
+use sha2::{Digest, Sha256, Sha512};
+
+const TAG_NONE: u8 = 0;
+const TAG_SHA256: u8 = 1;
+const TAG_SHA512: u8 = 2;
+
+/// Cryptographic digest algorithms that can be recorded in the header's
+/// `zro3` trailer alongside the legacy CRC32.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum HashAlgo {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    fn tag(self) -> u8 {
+        match self {
+            HashAlgo::Sha256 => TAG_SHA256,
+            HashAlgo::Sha512 => TAG_SHA512,
+        }
+    }
+
+    fn compute(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgo::Sha256 => Sha256::digest(payload).to_vec(),
+            HashAlgo::Sha512 => Sha512::digest(payload).to_vec(),
+        }
+    }
+}
+
+/// Write `tag, len, digest` into the trailer. `zro3` is 364 bytes, ample for
+/// a 64-byte SHA-512 digest plus this 2-byte prefix.
+pub(crate) fn write_digest(zro3: &mut [u8; 364], algo: HashAlgo, payload: &[u8]) {
+    let digest = algo.compute(payload);
+    zro3[0] = algo.tag();
+    zro3[1] = digest.len() as u8;
+    zro3[2..2 + digest.len()].copy_from_slice(&digest);
+}
+
+/// Recompute and check a digest previously stored by [`write_digest`].
+/// Returns `None` when the trailer carries no digest tag.
+pub(crate) fn verify_digest(zro3: &[u8; 364], payload: &[u8]) -> Option<bool> {
+    let algo = match zro3[0] {
+        TAG_SHA256 => HashAlgo::Sha256,
+        TAG_SHA512 => HashAlgo::Sha512,
+        TAG_NONE => return None,
+        _ => return None,
+    };
+    let len = zro3[1] as usize;
+    let stored = &zro3[2..2 + len];
+    Some(stored == algo.compute(payload).as_slice())
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+
+/// Errors produced while building or fixing a VisionFive2 SPL/image header.
+#[derive(Debug)]
+pub enum SplToolError {
+    /// Wraps any underlying filesystem or stream failure.
+    Io(std::io::Error),
+    /// The input SPL payload exceeds the space bootrom reserves for it.
+    FileTooLarge { size: u32, max: u32 },
+    /// The on-disk header could not be deserialized into `UBootSPLHeader`.
+    HeaderDeserialize,
+    /// The buffer handed in for header fixing is shorter than `size_of::<UBootSPLHeader>()` bytes.
+    InvalidHeaderLength,
+    /// The requested backup SPL offset falls inside the protective MBR + GPT
+    /// region and would overwrite the partition table.
+    BackupOffsetTooSmall { bofs: u32, min: u32 },
+    /// `--verify` found the stored CRC32 and/or digest did not match the
+    /// recomputed value.
+    VerifyFailed,
+}
+
+impl fmt::Display for SplToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplToolError::Io(e) => write!(f, "I/O error: {}", e),
+            SplToolError::FileTooLarge { size, max } => write!(
+                f,
+                "File too large! Please rebuild your SPL with -Os. Size is {} bytes, maximum allowed is {} bytes.",
+                size, max
+            ),
+            SplToolError::HeaderDeserialize => write!(f, "failed to deserialize header"),
+            SplToolError::InvalidHeaderLength => write!(f, "input buffer is not a valid header length"),
+            SplToolError::BackupOffsetTooSmall { bofs, min } => write!(
+                f,
+                "backup SPL offset 0x{:x} overlaps the GPT partition table, must be >= 0x{:x}",
+                bofs, min
+            ),
+            SplToolError::VerifyFailed => write!(f, "verification failed: CRC and/or digest mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for SplToolError {}
+
+impl From<std::io::Error> for SplToolError {
+    fn from(e: std::io::Error) -> Self {
+        SplToolError::Io(e)
+    }
+}
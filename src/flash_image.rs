@@ -0,0 +1,125 @@
+use crate::digest::HashAlgo;
+use crate::error::SplToolError;
+use crate::header::{self, SplHeader};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::mem::size_of;
+
+const SECTOR_SIZE: usize = 512;
+const GPT_ENTRY_COUNT: u32 = 128;
+const GPT_ENTRY_SIZE: u32 = 128;
+const GPT_ENTRIES_SECTORS: usize = (GPT_ENTRY_COUNT as usize * GPT_ENTRY_SIZE as usize) / SECTOR_SIZE;
+// LBA0 protective MBR + LBA1 GPT header + LBA2..34 partition entries.
+const GPT_RESERVED_SECTORS: usize = 2 + GPT_ENTRIES_SECTORS;
+
+/// Inputs for assembling a single flashable eMMC image.
+pub struct FlashImageConf {
+    pub spl_path: String,
+    /// The main U-Boot payload (e.g. `u-boot.itb`) that follows the SPL.
+    pub uboot_path: Option<String>,
+    pub out_path: String,
+    pub bofs: u32,
+    pub vers: u32,
+    pub hash: Option<HashAlgo>,
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+    let mut digest = crc.digest();
+    digest.update(bytes);
+    digest.finalize()
+}
+
+/// Build a protective MBR (LBA0) covering the whole disk, a primary GPT
+/// header (LBA1) and an empty partition-entry array (LBA2..34), sized for a
+/// disk of `total_sectors` 512-byte sectors.
+fn build_protective_mbr_and_gpt(total_sectors: u64) -> Vec<u8> {
+    let mut region = vec![0u8; GPT_RESERVED_SECTORS * SECTOR_SIZE];
+
+    // Protective MBR: a single partition entry covering the whole disk.
+    let mbr_part = 446;
+    region[mbr_part] = 0x00; // status: not bootable
+    region[mbr_part + 4] = 0xEE; // partition type: GPT protective
+    region[mbr_part + 8..mbr_part + 12].copy_from_slice(&1u32.to_le_bytes()); // first LBA
+    let mbr_sectors = (total_sectors - 1).min(u32::MAX as u64) as u32;
+    region[mbr_part + 12..mbr_part + 16].copy_from_slice(&mbr_sectors.to_le_bytes());
+    region[510] = 0x55;
+    region[511] = 0xAA;
+
+    // Partition entries (LBA2..34) are left zeroed: no partitions defined.
+    let entries_crc = crc32(&region[2 * SECTOR_SIZE..(2 + GPT_ENTRIES_SECTORS) * SECTOR_SIZE]);
+
+    // Primary GPT header (LBA1).
+    let hdr_start = SECTOR_SIZE;
+    let hdr_end = hdr_start + SECTOR_SIZE;
+    {
+        let hdr = &mut region[hdr_start..hdr_end];
+        hdr[0..8].copy_from_slice(b"EFI PART");
+        hdr[8..12].copy_from_slice(&0x00010000u32.to_le_bytes()); // revision 1.0
+        hdr[12..16].copy_from_slice(&92u32.to_le_bytes()); // header size
+        hdr[24..32].copy_from_slice(&1u64.to_le_bytes()); // this header's LBA
+        hdr[32..40].copy_from_slice(&(total_sectors - 1).to_le_bytes()); // backup header LBA
+        hdr[40..48].copy_from_slice(&(GPT_RESERVED_SECTORS as u64).to_le_bytes()); // first usable LBA
+        hdr[48..56]
+            .copy_from_slice(&(total_sectors - 1 - GPT_RESERVED_SECTORS as u64).to_le_bytes()); // last usable LBA
+        hdr[72..80].copy_from_slice(&2u64.to_le_bytes()); // partition entries LBA
+        hdr[80..84].copy_from_slice(&GPT_ENTRY_COUNT.to_le_bytes());
+        hdr[84..88].copy_from_slice(&GPT_ENTRY_SIZE.to_le_bytes());
+        hdr[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32(&hdr[0..92]);
+        hdr[16..20].copy_from_slice(&header_crc.to_le_bytes());
+    }
+
+    region
+}
+
+/// Known issue: on eMMC boot, bootrom reads sector 0 instead of partition 0.
+/// We exploit that by shipping a sector-0 region whose CRC check is
+/// deliberately wrong, so bootrom falls through to the real SPL (with a
+/// valid header) copied to the backup offset `bofs` - see `fix_image_header`
+/// for the in-place version of the same trick.
+pub fn build_flash_image(conf: &FlashImageConf) -> Result<(), SplToolError> {
+    let min_bofs = (GPT_RESERVED_SECTORS * SECTOR_SIZE) as u32;
+    if conf.bofs < min_bofs {
+        return Err(SplToolError::BackupOffsetTooSmall {
+            bofs: conf.bofs,
+            min: min_bofs,
+        });
+    }
+
+    let mut spl_payload = Vec::new();
+    File::open(&conf.spl_path)?.read_to_end(&mut spl_payload)?;
+    let backup_image = SplHeader::build(&spl_payload, conf.vers, conf.bofs, conf.hash)?;
+
+    let mut uboot_payload = Vec::new();
+    if let Some(path) = &conf.uboot_path {
+        File::open(path)?.read_to_end(&mut uboot_payload)?;
+    }
+
+    let hdr_size = size_of::<header::UBootSPLHeader>();
+    let backup_start = conf.bofs as usize;
+    let uboot_start = backup_start + backup_image.len();
+    let image_len = uboot_start + uboot_payload.len();
+    let total_sectors = image_len
+        .div_ceil(SECTOR_SIZE)
+        .max(GPT_RESERVED_SECTORS) as u64;
+
+    let mut image = vec![0u8; (total_sectors as usize * SECTOR_SIZE).max(image_len)];
+    let sector0 = build_protective_mbr_and_gpt(total_sectors);
+    image[..sector0.len()].copy_from_slice(&sector0);
+
+    // Only the header fields that alias into the GPT region are touched;
+    // the rest of the partition table built above is left untouched.
+    header::fix_image_header(&mut image[..hdr_size], Some(conf.bofs))?;
+
+    image[backup_start..backup_start + backup_image.len()].copy_from_slice(&backup_image);
+    image[uboot_start..uboot_start + uboot_payload.len()].copy_from_slice(&uboot_payload);
+
+    File::create(&conf.out_path)?.write_all(&image)?;
+    println!(
+        "IMG {} built: protective MBR + GPT at sector 0 (CRC invalidated), backup SPL at 0x{:x}.",
+        conf.out_path, conf.bofs
+    );
+    Ok(())
+}
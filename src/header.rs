@@ -0,0 +1,222 @@
+use crate::digest;
+use crate::error::SplToolError;
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::mem::size_of;
+use zerocopy::byteorder::little_endian::U32;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+/// Sentinel `crcs` value that deliberately fails bootrom's CRC check, used to
+/// force a fall-through to the backup SPL at `bofs`.
+pub const CRC_FAILED: u32 = 0x5A5A5A5A;
+
+/// Largest SPL payload bootrom will accept, leaving room for the header.
+const MAX_SPL_SIZE: u32 = (181072 - size_of::<UBootSPLHeader>() + 1) as u32;
+
+// Fixed, C-packed, 1024-byte on-disk layout (4+4+636+4+4+4+4+364). zerocopy
+// guarantees this struct serializes to exactly that many bytes with no
+// endianness surprises, so `as_bytes`/`read_from_bytes` are the wire format.
+#[repr(C, packed)]
+#[derive(FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout, Debug)]
+pub struct UBootSPLHeader {
+    // offset of spl header: 64+256+256 = 0x240
+    pub sofs: U32,
+    // SBL_BAK_OFFSET: Offset of backup SBL from Flash info start (from input_sbl_normal.cfg)
+    pub bofs: U32,
+    pub zro2: [u8; 636],
+    // version: shall be 0x01010101 (from https://doc-en.rvspace.org/VisionFive2/SWTRM/VisionFive2_SW_TRM/create_spl.html)
+    pub vers: U32,
+    // u-boot-spl.bin size in bytes
+    pub fsiz: U32,
+    // Offset from HDR to SPL_IMAGE, 0x400 (00 04 00 00) currently
+    pub res1: U32,
+    // CRC32 of u-boot-spl.bin
+    pub crcs: U32,
+    // reserved trailer; also carries an optional `--hash` digest (see digest.rs)
+    pub zro3: [u8; 364],
+}
+
+impl UBootSPLHeader {
+    pub fn new() -> Self {
+        Self {
+            sofs: U32::new(0x240),
+            bofs: U32::new(0),
+            zro2: [0; 636],
+            vers: U32::new(0),
+            fsiz: U32::new(0),
+            res1: U32::new(0x400),
+            crcs: U32::new(0),
+            zro3: [0; 364],
+        }
+    }
+}
+
+impl Default for UBootSPLHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder for a header+payload buffer ready to be written out as a
+/// `.normal.out` image.
+pub struct SplHeader;
+
+impl SplHeader {
+    /// Build a complete header+payload buffer for `payload`, computing its
+    /// size and CRC32 and recording `version`/`backup_offset` in the header.
+    /// Optionally records a `--hash`-style digest in the trailer.
+    pub fn build(
+        payload: &[u8],
+        version: u32,
+        backup_offset: u32,
+        hash: Option<digest::HashAlgo>,
+    ) -> Result<Vec<u8>, SplToolError> {
+        let f_size = payload.len() as u32;
+        if f_size > MAX_SPL_SIZE {
+            return Err(SplToolError::FileTooLarge {
+                size: f_size,
+                max: MAX_SPL_SIZE,
+            });
+        }
+        let mut hdr = UBootSPLHeader::new();
+        hdr.bofs = U32::new(backup_offset);
+        hdr.vers = U32::new(version);
+        hdr.fsiz = U32::new(f_size);
+        let crc32 = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        let mut crc_digest = crc32.digest();
+        crc_digest.update(payload);
+        hdr.crcs = U32::new(crc_digest.finalize());
+        if let Some(algo) = hash {
+            digest::write_digest(&mut hdr.zro3, algo, payload);
+        }
+        let mut out = Vec::with_capacity(size_of::<UBootSPLHeader>() + payload.len());
+        out.extend_from_slice(hdr.as_bytes());
+        out.extend_from_slice(payload);
+        Ok(out)
+    }
+}
+
+/// Patch an in-place header (the leading `size_of::<UBootSPLHeader>()` bytes
+/// of `buf`) for eMMC backup boot: deliberately invalidate its CRC32 so
+/// bootrom falls through to the backup SPL, optionally updating `bofs`.
+pub fn fix_image_header(buf: &mut [u8], backup_offset: Option<u32>) -> Result<(), SplToolError> {
+    let hdr_size = size_of::<UBootSPLHeader>();
+    if buf.len() < hdr_size {
+        return Err(SplToolError::InvalidHeaderLength);
+    }
+    let mut hdr = UBootSPLHeader::read_from_bytes(&buf[..hdr_size])
+        .map_err(|_| SplToolError::HeaderDeserialize)?;
+    if let Some(bofs) = backup_offset {
+        if bofs != 0 {
+            hdr.bofs = U32::new(bofs);
+        }
+    }
+    hdr.crcs = U32::new(CRC_FAILED);
+    buf[..hdr_size].copy_from_slice(hdr.as_bytes());
+    Ok(())
+}
+
+/// Result of re-parsing a header and checking it against its payload.
+pub struct VerifyReport {
+    pub sofs: u32,
+    pub bofs: u32,
+    pub vers: u32,
+    pub fsiz: u32,
+    pub res1: u32,
+    pub crcs: u32,
+    /// `None` when `crcs` carries the `CRC_FAILED` sentinel rather than a
+    /// real checksum. `Some(true)` means the payload's CRC32 matches.
+    pub crc_ok: Option<bool>,
+    /// `None` when the trailer carries no digest tag, or when `crcs` carries
+    /// the `CRC_FAILED` sentinel (the digest is then not checked).
+    pub digest_ok: Option<bool>,
+}
+
+/// Parse the leading header out of `hdr_bytes` and check it against `payload`.
+pub fn verify_header(hdr_bytes: &[u8], payload: &[u8]) -> Result<VerifyReport, SplToolError> {
+    let hdr = UBootSPLHeader::read_from_bytes(hdr_bytes).map_err(|_| SplToolError::HeaderDeserialize)?;
+    let crcs = hdr.crcs.get();
+    // When crcs carries the CRC_FAILED sentinel, no real payload was read
+    // for this header (see write_img_hdr/verify_img_hdr), so there is
+    // nothing to check the digest against either.
+    let (crc_ok, digest_ok) = if crcs == CRC_FAILED {
+        (None, None)
+    } else {
+        let crc32 = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        let mut crc_digest = crc32.digest();
+        crc_digest.update(payload);
+        (
+            Some(crc_digest.finalize() == crcs),
+            digest::verify_digest(&hdr.zro3, payload),
+        )
+    };
+    Ok(VerifyReport {
+        sofs: hdr.sofs.get(),
+        bofs: hdr.bofs.get(),
+        vers: hdr.vers.get(),
+        fsiz: hdr.fsiz.get(),
+        res1: hdr.res1.get(),
+        crcs,
+        crc_ok,
+        digest_ok,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::HashAlgo;
+
+    #[test]
+    fn build_then_verify_round_trips() {
+        let payload = b"fake u-boot-spl.bin contents".to_vec();
+        let image = SplHeader::build(&payload, 0x01010101, 0x200000, None).unwrap();
+        let hdr_size = size_of::<UBootSPLHeader>();
+        let report = verify_header(&image[..hdr_size], &payload).unwrap();
+
+        assert_eq!(report.vers, 0x01010101);
+        assert_eq!(report.bofs, 0x200000);
+        assert_eq!(report.fsiz, payload.len() as u32);
+        assert_eq!(report.crc_ok, Some(true));
+        assert_eq!(report.digest_ok, None);
+    }
+
+    #[test]
+    fn fix_image_header_invalidates_crc_and_skips_digest_check() {
+        let payload = b"fake u-boot-spl.bin contents".to_vec();
+        let mut image = SplHeader::build(&payload, 0x01010101, 0x200000, Some(HashAlgo::Sha256)).unwrap();
+        let hdr_size = size_of::<UBootSPLHeader>();
+
+        fix_image_header(&mut image[..hdr_size], Some(0x400000)).unwrap();
+        let report = verify_header(&image[..hdr_size], &[]).unwrap();
+
+        assert_eq!(report.crcs, CRC_FAILED);
+        assert_eq!(report.bofs, 0x400000);
+        assert_eq!(report.crc_ok, None);
+        assert_eq!(report.digest_ok, None);
+    }
+
+    #[test]
+    fn digest_tag_is_checked_when_crc_is_valid() {
+        let payload = b"fake u-boot-spl.bin contents".to_vec();
+        let image = SplHeader::build(&payload, 0x01010101, 0x200000, Some(HashAlgo::Sha256)).unwrap();
+        let hdr_size = size_of::<UBootSPLHeader>();
+
+        let report = verify_header(&image[..hdr_size], &payload).unwrap();
+        assert_eq!(report.crc_ok, Some(true));
+        assert_eq!(report.digest_ok, Some(true));
+
+        let mut tampered = payload.clone();
+        tampered[0] ^= 0xff;
+        let report = verify_header(&image[..hdr_size], &tampered).unwrap();
+        assert_eq!(report.digest_ok, Some(false));
+    }
+
+    #[test]
+    fn fix_image_header_rejects_short_buffer() {
+        let mut short = vec![0u8; 16];
+        assert!(matches!(
+            fix_image_header(&mut short, None),
+            Err(SplToolError::InvalidHeaderLength)
+        ));
+    }
+}
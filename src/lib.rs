@@ -0,0 +1,11 @@
+//! Reusable core of the VisionFive2 SPL header tool: build and fix
+//! `UBootSPLHeader`-prefixed images in memory, without going through the
+//! CLI. `src/main.rs` is a thin clap front-end over this library.
+
+pub mod digest;
+pub mod error;
+pub mod flash_image;
+mod header;
+
+pub use error::SplToolError;
+pub use header::{fix_image_header, verify_header, SplHeader, UBootSPLHeader, VerifyReport, CRC_FAILED};
@@ -1,15 +1,13 @@
-use clap::{arg, ArgAction, Parser};
+use clap::{ArgAction, Parser};
 use clap_num::maybe_hex;
-use crc::{Crc, CRC_32_ISO_HDLC};
-use serde::{Deserialize, Serialize};
+use log::error;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
-use log::error;
-
-// const DEF_VER: u32 = 0x01010101;
-// const DEF_BACKUP: u32 = 0x200000;
-const CRC_FAILED: u32 = 0x5A5A5A5A;
+use std::process::ExitCode;
+use zerocopy::FromBytes;
+use vf2_header::digest::HashAlgo;
+use vf2_header::{flash_image, SplHeader, SplToolError, UBootSPLHeader, CRC_FAILED};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None, disable_version_flag = true, arg_required_else_help = true)]
@@ -18,49 +16,22 @@ struct Args {
     c: bool,
     #[arg(short, long = "fix-imghdr", help = "fixed img hdr for emmc boot", action = ArgAction::SetTrue)]
     i: bool,
+    #[arg(short = 't', long = "verify", help = "verify a .normal.out image against its stored CRC32", action = ArgAction::SetTrue)]
+    t: bool,
+    #[arg(short, long = "make-image", help = "build a full flashable eMMC image", action = ArgAction::SetTrue)]
+    m: bool,
     #[arg(short, long = "spl-bak-addr", help = "set backup SPL addr", value_parser = maybe_hex::< u32 >, default_value = "0x200000")]
     a: u32,
     #[arg(short, long = "version", help = "set version", value_parser = maybe_hex::< u32 >, default_value = "0x01010101")]
     v: u32,
     #[arg(short, long = "file", help = "input file name")]
     f: String,
-}
-
-#[repr(C)]
-#[derive(Serialize, Deserialize, Debug)]
-struct UBootSPLHeader {
-    // offset of spl header: 64+256+256 = 0x240
-    sofs: u32,
-    // SBL_BAK_OFFSET: Offset of backup SBL from Flash info start (from input_sbl_normal.cfg)
-    bofs: u32,
-    #[serde(with = "serde_arrays")]
-    zro2: [u8; 636],
-    // version: shall be 0x01010101 (from https://doc-en.rvspace.org/VisionFive2/SWTRM/VisionFive2_SW_TRM/create_spl.html)
-    vers: u32,
-    // u-boot-spl.bin size in bytes
-    fsiz: u32,
-    // Offset from HDR to SPL_IMAGE, 0x400 (00 04 00 00) currently
-    res1: u32,
-    // CRC32 of u-boot-spl.bin
-    crcs: u32,
-    #[serde(with = "serde_arrays")]
-    zro3: [u8; 364],
-}
-
-
-impl UBootSPLHeader {
-    fn new() -> Self {
-        Self {
-            sofs: 0x240u32.to_le(),
-            bofs: 0,
-            zro2: [0; 636],
-            vers: 0,
-            fsiz: 0,
-            res1: 0x400u32.to_le(),
-            crcs: 0,
-            zro3: [0; 364],
-        }
-    }
+    #[arg(short, long = "uboot", help = "U-Boot payload to append after the backup SPL (--make-image only)")]
+    u: Option<String>,
+    #[arg(short, long = "out", help = "output image file name (--make-image only)")]
+    o: Option<String>,
+    #[arg(long = "hash", help = "record a SHA-256/SHA-512 digest of the payload in the header trailer")]
+    hash: Option<HashAlgo>,
 }
 
 struct HeaderConf {
@@ -69,6 +40,11 @@ struct HeaderConf {
     bofs: u32,
     create_hdr: bool,
     fix_img_hdr: bool,
+    verify: bool,
+    make_image: bool,
+    uboot: Option<String>,
+    out: Option<String>,
+    hash: Option<HashAlgo>,
 }
 
 impl From<Args> for HeaderConf {
@@ -79,73 +55,112 @@ impl From<Args> for HeaderConf {
             vers: args.v.to_le(),
             create_hdr: args.c,
             fix_img_hdr: args.i,
+            verify: args.t,
+            make_image: args.m,
+            uboot: args.u,
+            out: args.o,
+            hash: args.hash,
         }
     }
 }
 
-fn write_spl_hdr(conf: &HeaderConf) {
-    let mut spl_hdr: UBootSPLHeader = UBootSPLHeader::new();
-    spl_hdr.bofs = conf.bofs;
-    spl_hdr.vers = conf.vers;
+fn write_spl_hdr(conf: &HeaderConf) -> Result<(), SplToolError> {
+    let mut contents = Vec::new();
+    File::open(conf.name.clone())?.read_to_end(&mut contents)?;
+    let out = SplHeader::build(contents.as_slice(), conf.vers, conf.bofs, conf.hash)?;
     println!(
-        "spl_hdr.sofs: 0x{:x}, spl_hdr.bofs: 0x{:x}, spl_hdr.vers: 0x{:x} name:{}",
-        spl_hdr.sofs,
-        spl_hdr.bofs,
-        spl_hdr.vers,
+        "spl_hdr.bofs: 0x{:x}, spl_hdr.vers: 0x{:x} name:{}",
+        conf.bofs,
+        conf.vers,
         conf.name.clone()
     );
-    let mut file = File::open(conf.name.clone()).unwrap(); //fixme: error case handle
-    let metadata = file.metadata().unwrap(); //fixme: error case handle
-    let max_size = (181072 - size_of::<UBootSPLHeader>() + 1) as u32;
-    let f_size = metadata.len() as u32;
-    if f_size > max_size {
-        panic!("File too large! Please rebuild your SPL with -Os. Maximum allowed size is {} bytes.", max_size);
-    }
-    spl_hdr.fsiz = f_size.to_le();
-    let mut contents = Vec::new();
-    let _res = file.read_to_end(&mut contents); //fixme: error case handle
-    let mut file = File::create(format!("{}.normal.out", conf.name.clone())).unwrap(); //fixme: error case handle
-    let crc32 = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-    let mut digest = crc32.digest();
-    digest.update(contents.as_slice());
-    spl_hdr.crcs = digest.finalize().to_le();
-    let v = bincode::serialize(&spl_hdr).unwrap(); //fixme: error case handle
-    let _res = file.write(v.as_slice()); //fixme: error case handle
-    let _res = file.write(contents.as_slice()); //fixme: error case handle
+    File::create(format!("{}.normal.out", conf.name.clone()))?.write_all(&out)?;
+    Ok(())
 }
 
 /// When starting with emmc, bootrom will read 0x0 instead of partition 0. (Known issues).
 /// Read GPT PMBR+Header, then write the backup address at 0x4, and write the wrong CRC
 /// check value at 0x290, so that bootrom CRC check fails and jump to the backup address
 /// to load the real spl.
-fn write_img_hdr(conf: &HeaderConf) {
+fn write_img_hdr(conf: &HeaderConf) -> Result<(), SplToolError> {
     let mut file = File::options()
         .read(true)
         .write(true)
-        .open(conf.name.clone())
-        .unwrap(); //fixme: error case handle
+        .open(conf.name.clone())?;
     let mut contents = vec![0u8; size_of::<UBootSPLHeader>()];
-    let _res = file.read(&mut contents); //fixme: error case handle
-    let mut img_hdr: UBootSPLHeader = bincode::deserialize(contents.as_slice()).unwrap();//fixme: error case handle
-    if conf.bofs != 0 {
-        img_hdr.bofs = conf.bofs;
-    }
-    img_hdr.crcs = CRC_FAILED.to_le();
-    let _res = file.seek(SeekFrom::Start(0)); //fixme: error case handle
-    let v = bincode::serialize(&img_hdr).unwrap(); //fixme: error case handle
-    let _res = file.write(v.as_slice()); //fixme: error case handle
+    file.read_exact(&mut contents)?;
+    let bofs = if conf.bofs != 0 { Some(conf.bofs) } else { None };
+    vf2_header::fix_image_header(&mut contents, bofs)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&contents)?;
     println!("IMG {} fixed hdr successfully.", conf.name.clone());
+    Ok(())
 }
 
-fn main() {
+/// Re-parse a `.normal.out` image, recompute the CRC32 over its payload and
+/// report whether it matches the header's stored `crcs`.
+fn verify_img_hdr(conf: &HeaderConf) -> Result<(), SplToolError> {
+    let mut file = File::open(conf.name.clone())?;
+    let mut hdr_bytes = vec![0u8; size_of::<UBootSPLHeader>()];
+    file.read_exact(&mut hdr_bytes)?;
+    let peek = UBootSPLHeader::read_from_bytes(hdr_bytes.as_slice())
+        .map_err(|_| SplToolError::HeaderDeserialize)?;
+    let mut payload = Vec::new();
+    if peek.crcs.get() != CRC_FAILED {
+        file.seek(SeekFrom::Start(peek.res1.get() as u64))?;
+        payload = vec![0u8; peek.fsiz.get() as usize];
+        file.read_exact(&mut payload)?;
+    }
+    let report = vf2_header::verify_header(hdr_bytes.as_slice(), payload.as_slice())?;
+    println!(
+        "sofs: 0x{:x}, bofs: 0x{:x}, vers: 0x{:x}, fsiz: 0x{:x}, res1: 0x{:x}, crcs: 0x{:x}",
+        report.sofs, report.bofs, report.vers, report.fsiz, report.res1, report.crcs
+    );
+    match report.crc_ok {
+        None => println!("CRC: intentionally invalidated for eMMC backup boot"),
+        Some(true) => println!("CRC: OK"),
+        Some(false) => println!("CRC: FAILED"),
+    }
+    match report.digest_ok {
+        Some(true) => println!("Digest: OK"),
+        Some(false) => println!("Digest: FAILED"),
+        None => {}
+    }
+    if report.crc_ok == Some(false) || report.digest_ok == Some(false) {
+        return Err(SplToolError::VerifyFailed);
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
     env_logger::init();
     let hdr_conf: HeaderConf = args.into();
-    if hdr_conf.create_hdr {
-        write_spl_hdr(&hdr_conf);
-        return;
-    }
-    if hdr_conf.fix_img_hdr {
-        write_img_hdr(&hdr_conf);
+    let result = if hdr_conf.create_hdr {
+        write_spl_hdr(&hdr_conf)
+    } else if hdr_conf.fix_img_hdr {
+        write_img_hdr(&hdr_conf)
+    } else if hdr_conf.verify {
+        verify_img_hdr(&hdr_conf)
+    } else if hdr_conf.make_image {
+        let out = hdr_conf
+            .out
+            .clone()
+            .unwrap_or_else(|| format!("{}.img", hdr_conf.name));
+        flash_image::build_flash_image(&flash_image::FlashImageConf {
+            spl_path: hdr_conf.name.clone(),
+            uboot_path: hdr_conf.uboot.clone(),
+            out_path: out,
+            bofs: hdr_conf.bofs,
+            vers: hdr_conf.vers,
+            hash: hdr_conf.hash,
+        })
+    } else {
+        Ok(())
+    };
+    if let Err(e) = result {
+        error!("{}", e);
+        return ExitCode::FAILURE;
     }
+    ExitCode::SUCCESS
 }